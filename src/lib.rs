@@ -1,18 +1,95 @@
+use async_trait::async_trait;
+use prometheus::{Encoder, IntCounter, IntGauge, Registry, TextEncoder};
 use rand::Rng;
-use std::cell::Cell;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt::Display;
-use std::rc::Rc;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock as StdRwLock};
 use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, mpsc, oneshot, RwLock};
 
-pub trait Device: Display {
+/// A reading failure while polling a device, e.g. over a network transport.
+#[derive(Error, Debug)]
+pub enum PollError {
+    #[error("could not reach the device: {0}")]
+    Connection(#[from] std::io::Error),
+    #[error("could not parse the reading: {0}")]
+    Parse(String),
+    #[error("the device actor is no longer running")]
+    Unavailable,
+}
+
+#[async_trait]
+pub trait Device: Display + Send + Sync {
     fn name(&self) -> String;
     fn poll(&self);
+    /// Refreshes the device's reading, possibly over a transport, and returns
+    /// the new value.
+    async fn poll_async(&self) -> Result<f64, PollError>;
+    /// The device's most recent reading.
+    fn reading(&self) -> f64;
+    /// The serializable kind tag used to reconstruct this device from disk.
+    fn kind(&self) -> DeviceKind;
+    /// A serializable snapshot of the device's name, kind and last reading.
+    fn record(&self) -> DeviceRecord {
+        DeviceRecord {
+            name: self.name(),
+            kind: self.kind(),
+            reading: self.reading(),
+        }
+    }
+}
+
+/// The kind of a device, persisted so a device can be rebuilt on load.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum DeviceKind {
+    Socket,
+    Thermometer,
+    RemoteSocket { address: String },
+}
+
+/// A serializable view of a single device.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DeviceRecord {
+    pub name: String,
+    pub kind: DeviceKind,
+    pub reading: f64,
+}
+
+/// A serializable view of a single room: its devices plus the room's own
+/// configuration, so empty-but-configured rooms survive a reload.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RoomSnapshot {
+    pub devices: Vec<DeviceRecord>,
+    pub capacity: Option<usize>,
+    pub restricted: bool,
+    pub master: Option<String>,
+}
+
+/// A serializable view of a whole house's room/device topology.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HouseSnapshot {
+    pub name: String,
+    pub rooms: HashMap<String, RoomSnapshot>,
+}
+
+/// Rebuilds a live device from its persisted record.
+fn device_from_record(record: &DeviceRecord) -> Arc<dyn Device> {
+    match &record.kind {
+        DeviceKind::Socket => Arc::new(Socket::restore(&record.name, record.reading)),
+        DeviceKind::Thermometer => Arc::new(Thermometer::restore(&record.name, record.reading)),
+        DeviceKind::RemoteSocket { address } => {
+            Arc::new(RemoteSocket::restore(&record.name, address, record.reading))
+        }
+    }
 }
 
 pub struct Socket {
     name: String,
-    voltage: Cell<f64>,
+    voltage: StdRwLock<f64>,
 }
 
 impl Display for Socket {
@@ -20,17 +97,29 @@ impl Display for Socket {
         f.write_fmt(format_args!(
             "SOCKET:\n    name: {}\n    voltage: {:.2}\n",
             self.name,
-            self.voltage.get()
+            *self.voltage.read().unwrap()
         ))
     }
 }
 
+#[async_trait]
 impl Device for Socket {
     fn name(&self) -> String {
         self.name.clone()
     }
     fn poll(&self) {
-        self.voltage.set(Self::rand_voltage());
+        *self.voltage.write().unwrap() = Self::rand_voltage();
+    }
+    async fn poll_async(&self) -> Result<f64, PollError> {
+        let voltage = Self::rand_voltage();
+        *self.voltage.write().unwrap() = voltage;
+        Ok(voltage)
+    }
+    fn reading(&self) -> f64 {
+        *self.voltage.read().unwrap()
+    }
+    fn kind(&self) -> DeviceKind {
+        DeviceKind::Socket
     }
 }
 
@@ -40,7 +129,15 @@ impl Socket {
 
         Socket {
             name: name.to_owned(),
-            voltage: voltage.into(),
+            voltage: StdRwLock::new(voltage),
+        }
+    }
+
+    /// Rebuilds a socket from a persisted reading.
+    pub fn restore(name: &str, voltage: f64) -> Self {
+        Socket {
+            name: name.to_owned(),
+            voltage: StdRwLock::new(voltage),
         }
     }
 
@@ -50,30 +147,278 @@ impl Socket {
     }
 }
 
-trait DeviceStorage<T: Device> {
-    fn add(&mut self, device: Rc<T>);
+pub struct Thermometer {
+    name: String,
+    temperature: StdRwLock<f64>,
 }
 
-pub struct SocketStorage {
-    devices: Vec<Rc<Socket>>,
+impl Display for Thermometer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "THERMOMETER:\n    name: {}\n    temperature: {:.2}\n",
+            self.name,
+            *self.temperature.read().unwrap()
+        ))
+    }
+}
+
+#[async_trait]
+impl Device for Thermometer {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+    fn poll(&self) {
+        *self.temperature.write().unwrap() = Self::rand_temperature();
+    }
+    async fn poll_async(&self) -> Result<f64, PollError> {
+        let temperature = Self::rand_temperature();
+        *self.temperature.write().unwrap() = temperature;
+        Ok(temperature)
+    }
+    fn reading(&self) -> f64 {
+        *self.temperature.read().unwrap()
+    }
+    fn kind(&self) -> DeviceKind {
+        DeviceKind::Thermometer
+    }
+}
+
+impl Thermometer {
+    pub fn new(name: &str) -> Self {
+        let temperature = Self::rand_temperature();
+
+        Thermometer {
+            name: name.to_owned(),
+            temperature: StdRwLock::new(temperature),
+        }
+    }
+
+    /// Rebuilds a thermometer from a persisted reading.
+    pub fn restore(name: &str, temperature: f64) -> Self {
+        Thermometer {
+            name: name.to_owned(),
+            temperature: StdRwLock::new(temperature),
+        }
+    }
+
+    fn rand_temperature() -> f64 {
+        let mut r = rand::thread_rng();
+        r.gen_range(-30.0..50.0)
+    }
+}
+
+/// A socket whose voltage is read from a real network endpoint rather than
+/// invented locally.
+///
+/// The wire protocol is line based: the socket sends `GET\n` and expects a
+/// `VOLTS <f64>\n` reply.
+pub struct RemoteSocket {
+    name: String,
+    address: String,
+    voltage: StdRwLock<f64>,
 }
 
-impl Default for SocketStorage {
+impl Display for RemoteSocket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "REMOTE SOCKET:\n    name: {}\n    address: {}\n    voltage: {:.2}\n",
+            self.name,
+            self.address,
+            *self.voltage.read().unwrap()
+        ))
+    }
+}
+
+#[async_trait]
+impl Device for RemoteSocket {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+    fn poll(&self) {
+        // The synchronous path cannot touch the network; the last reading is
+        // retained until the next `poll_async`.
+    }
+    async fn poll_async(&self) -> Result<f64, PollError> {
+        let stream = TcpStream::connect(&self.address).await?;
+        let mut stream = BufReader::new(stream);
+        stream.get_mut().write_all(b"GET\n").await?;
+        let mut line = String::new();
+        stream.read_line(&mut line).await?;
+        let voltage = Self::parse_voltage(&line)?;
+        *self.voltage.write().unwrap() = voltage;
+        Ok(voltage)
+    }
+    fn reading(&self) -> f64 {
+        *self.voltage.read().unwrap()
+    }
+    fn kind(&self) -> DeviceKind {
+        DeviceKind::RemoteSocket {
+            address: self.address.clone(),
+        }
+    }
+}
+
+impl RemoteSocket {
+    pub fn new(name: &str, address: &str) -> Self {
+        RemoteSocket {
+            name: name.to_owned(),
+            address: address.to_owned(),
+            voltage: StdRwLock::new(0.0),
+        }
+    }
+
+    /// Rebuilds a remote socket from a persisted address and reading.
+    pub fn restore(name: &str, address: &str, voltage: f64) -> Self {
+        RemoteSocket {
+            name: name.to_owned(),
+            address: address.to_owned(),
+            voltage: StdRwLock::new(voltage),
+        }
+    }
+
+    /// Parses a `VOLTS <f64>` reply line into a voltage reading.
+    fn parse_voltage(line: &str) -> Result<f64, PollError> {
+        let reading = line
+            .trim()
+            .strip_prefix("VOLTS ")
+            .ok_or_else(|| PollError::Parse(line.trim().to_owned()))?;
+        reading
+            .parse::<f64>()
+            .map_err(|e| PollError::Parse(e.to_string()))
+    }
+}
+
+/// A command delivered to a device's background actor.
+enum DeviceCommand {
+    Poll {
+        reply: oneshot::Sender<Result<f64, PollError>>,
+    },
+}
+
+/// A cloneable handle to a single device driven by its own background task.
+///
+/// Every device gets a command channel; sending `Poll` makes the actor refresh
+/// the device's reading without blocking the caller, so the house can fan out a
+/// poll across every device concurrently.
+#[derive(Clone)]
+pub struct DeviceHandle {
+    device: Arc<dyn Device>,
+    commands: mpsc::Sender<DeviceCommand>,
+}
+
+impl DeviceHandle {
+    /// Spawns the device's actor and returns a handle to it.
+    pub fn spawn(device: Arc<dyn Device>) -> Self {
+        let (commands, mut rx) = mpsc::channel(16);
+        let worker = device.clone();
+        tokio::spawn(async move {
+            while let Some(cmd) = rx.recv().await {
+                match cmd {
+                    DeviceCommand::Poll { reply } => {
+                        let _ = reply.send(worker.poll_async().await);
+                    }
+                }
+            }
+        });
+        DeviceHandle { device, commands }
+    }
+
+    pub fn name(&self) -> String {
+        self.device.name()
+    }
+
+    /// A serializable snapshot of the underlying device.
+    pub fn record(&self) -> DeviceRecord {
+        self.device.record()
+    }
+
+    /// Asks the device's actor to refresh its reading, returning the new value.
+    pub async fn poll(&self) -> Result<f64, PollError> {
+        let (reply, rx) = oneshot::channel();
+        if self
+            .commands
+            .send(DeviceCommand::Poll { reply })
+            .await
+            .is_err()
+        {
+            return Err(PollError::Unavailable);
+        }
+        rx.await.unwrap_or(Err(PollError::Unavailable))
+    }
+}
+
+impl Display for DeviceHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.device.fmt(f)
+    }
+}
+
+trait DeviceStorage {
+    fn add(&mut self, device: DeviceHandle);
+    fn remove(&mut self, device: &DeviceHandle);
+}
+
+pub struct DeviceStore {
+    devices: Vec<DeviceHandle>,
+}
+
+impl Default for DeviceStore {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl SocketStorage {
+impl DeviceStore {
     pub fn new() -> Self {
-        SocketStorage { devices: vec![] }
+        DeviceStore { devices: vec![] }
     }
 }
 
-impl DeviceStorage<Socket> for SocketStorage {
-    fn add(&mut self, device: Rc<Socket>) {
+impl DeviceStorage for DeviceStore {
+    fn add(&mut self, device: DeviceHandle) {
         self.devices.push(device)
     }
+    fn remove(&mut self, device: &DeviceHandle) {
+        self.devices
+            .retain(|d| !Arc::ptr_eq(&d.device, &device.device));
+    }
+}
+
+/// A failure while reading or writing the house's backing store.
+#[derive(Error, Debug)]
+pub enum StorageError {
+    #[error("storage io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("storage (de)serialization error: {0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("metrics registration error: {0}")]
+    Metrics(#[from] prometheus::Error),
+}
+
+/// A file-backed store for a house's topology, held by the house so topology
+/// changes can be written through to disk.
+#[derive(Clone)]
+pub struct Storage {
+    path: PathBuf,
+}
+
+impl Storage {
+    pub fn open(path: impl AsRef<Path>) -> Self {
+        Storage {
+            path: path.as_ref().to_owned(),
+        }
+    }
+
+    pub async fn save(&self, snapshot: &HouseSnapshot) -> Result<(), StorageError> {
+        let data = serde_json::to_vec_pretty(snapshot)?;
+        tokio::fs::write(&self.path, data).await?;
+        Ok(())
+    }
+
+    pub async fn load(&self) -> Result<HouseSnapshot, StorageError> {
+        let data = tokio::fs::read(&self.path).await?;
+        Ok(serde_json::from_slice(&data)?)
+    }
 }
 
 #[derive(Error, Debug)]
@@ -84,95 +429,602 @@ pub struct WhereAmI(String);
 #[error("The room {0} does not exist")]
 pub struct NoSuchRoom(#[from] WhereAmI);
 
+/// Why a device could not be added to a room: a typed reason the caller can act
+/// on rather than a fire-and-forget failure.
 #[derive(Error, Debug)]
-#[error("The room already contains this device: {0}")]
-pub struct AlreadyContainsDevice(String);
+pub enum JoinRoomError {
+    #[error("The room is full")]
+    Full,
+    #[error("The room is restricted")]
+    Restricted,
+    #[error("The room already contains this device: {0}")]
+    AlreadyContainsDevice(String),
+}
 
-pub struct House {
+/// The outcome of removing a device from a room: either the room emptied and
+/// was torn down, or it remains and we report whether the master left and who,
+/// if anyone, was promoted in its place.
+#[derive(Debug, PartialEq, Eq)]
+pub enum LeaveRoomResult {
+    /// The room did not exist.
+    RoomDoesNotExist,
+    /// The device was not in the room.
+    DeviceNotPresent,
+    /// The last device left, so the room was removed.
+    RoomRemoved,
+    /// The room still holds devices.
+    RoomRemains {
+        was_master: bool,
+        new_master: Option<String>,
+    },
+}
+
+/// Prometheus gauges and counters tracking a house's live topology.
+///
+/// The gauges are registered into the caller's [`Registry`] at construction;
+/// a clone of each is held here so the live topology can be updated from the
+/// hot paths.
+#[derive(Clone)]
+pub struct Metrics {
+    rooms_active: IntGauge,
+    devices_active: IntGauge,
+    poll_total: IntCounter,
+    registry: Registry,
+}
+
+impl Metrics {
+    fn register(registry: &mut Registry) -> Result<Self, prometheus::Error> {
+        let rooms_active = IntGauge::new("house_rooms_active", "Number of rooms in the house")?;
+        let devices_active =
+            IntGauge::new("house_devices_active", "Number of devices in the house")?;
+        let poll_total =
+            IntCounter::new("house_poll_total", "Total number of house polls performed")?;
+        registry.register(Box::new(rooms_active.clone()))?;
+        registry.register(Box::new(devices_active.clone()))?;
+        registry.register(Box::new(poll_total.clone()))?;
+        Ok(Metrics {
+            rooms_active,
+            devices_active,
+            poll_total,
+            registry: registry.clone(),
+        })
+    }
+}
+
+/// A room: its devices, an optional capacity, a restricted flag, and the name
+/// of the device currently acting as the room's master.
+#[derive(Default)]
+pub struct Room {
+    devices: HashMap<String, DeviceHandle>,
+    capacity: Option<usize>,
+    restricted: bool,
+    master: Option<String>,
+}
+
+/// A live voltage update for a single device, broadcast to every subscriber of
+/// its room.
+#[derive(Clone, Debug)]
+pub struct DeviceUpdate {
+    pub room: String,
+    pub device: String,
+    pub voltage: f64,
+}
+
+/// The shared state behind a [`HouseHandle`].
+pub struct HouseInner {
     pub name: String,
-    pub device_by_room: HashMap<String, HashMap<String, Rc<dyn Device>>>,
-    pub sockets: SocketStorage,
+    pub device_by_room: HashMap<String, Room>,
+    pub devices: DeviceStore,
+    pub metrics: Metrics,
+    pub storage: Option<Storage>,
+    pub subscribers: HashMap<String, broadcast::Sender<DeviceUpdate>>,
 }
 
-impl House {
-    pub fn new(name: &str) -> Self {
-        House {
-            name: name.to_owned(),
-            device_by_room: HashMap::new(),
-            sockets: SocketStorage::new(),
+impl HouseInner {
+    /// Builds a serializable snapshot of the current topology.
+    fn snapshot(&self) -> HouseSnapshot {
+        let rooms = self
+            .device_by_room
+            .iter()
+            .map(|(room, state)| {
+                (
+                    room.clone(),
+                    RoomSnapshot {
+                        devices: state.devices.values().map(|d| d.record()).collect(),
+                        capacity: state.capacity,
+                        restricted: state.restricted,
+                        master: state.master.clone(),
+                    },
+                )
+            })
+            .collect();
+        HouseSnapshot {
+            name: self.name.clone(),
+            rooms,
         }
     }
 
-    pub fn rooms(&self) -> Vec<String> {
-        self.device_by_room.keys().cloned().collect()
+    /// Writes the current topology through to the backing store, if one is set.
+    async fn persist(&self) -> Result<(), StorageError> {
+        if let Some(storage) = &self.storage {
+            storage.save(&self.snapshot()).await?;
+        }
+        Ok(())
     }
+}
+
+/// A cloneable, thread-safe handle to a house.
+///
+/// Every clone shares the same [`HouseInner`] behind an `Arc<RwLock<..>>`, so
+/// the house can be driven from several async tasks at once.
+#[derive(Clone)]
+pub struct HouseHandle {
+    inner: Arc<RwLock<HouseInner>>,
+}
 
-    pub fn devices(&self, room: &str) -> Result<Vec<String>, NoSuchRoom> {
-        let devices = self
+/// A cloneable handle to a single room of a house, mirroring the state behind
+/// its owning [`HouseHandle`].
+#[derive(Clone)]
+pub struct RoomHandle {
+    name: String,
+    inner: Arc<RwLock<HouseInner>>,
+}
+
+impl HouseHandle {
+    pub fn new(name: &str, registry: &mut Registry) -> Result<Self, prometheus::Error> {
+        Ok(HouseHandle {
+            inner: Arc::new(RwLock::new(HouseInner {
+                name: name.to_owned(),
+                device_by_room: HashMap::new(),
+                devices: DeviceStore::new(),
+                metrics: Metrics::register(registry)?,
+                storage: None,
+                subscribers: HashMap::new(),
+            })),
+        })
+    }
+
+    /// Attaches a backing store so subsequent topology changes are written
+    /// through to disk.
+    pub async fn attach_storage(&self, storage: Storage) {
+        self.inner.write().await.storage = Some(storage);
+    }
+
+    /// Builds a serializable snapshot of the house.
+    pub async fn snapshot(&self) -> HouseSnapshot {
+        self.inner.read().await.snapshot()
+    }
+
+    /// Serializes the house's topology to `path`.
+    pub async fn save(&self, path: impl AsRef<Path>) -> Result<(), StorageError> {
+        Storage::open(path).save(&self.snapshot().await).await
+    }
+
+    /// Loads a house's topology from `path`, registering fresh metrics.
+    pub async fn load(
+        path: impl AsRef<Path>,
+        registry: &mut Registry,
+    ) -> Result<HouseHandle, StorageError> {
+        let snapshot = Storage::open(path).load().await?;
+        let house = HouseHandle::new(&snapshot.name, registry)?;
+        for (room, state) in &snapshot.rooms {
+            // recreate the room itself even when it holds no devices
+            house.get_or_create_room(room).await;
+            for record in &state.devices {
+                let _ = house
+                    .add_device_to_room(device_from_record(record), room)
+                    .await;
+            }
+            // restore the room's own configuration once its devices are in
+            // place (so capacity/restricted don't reject the reload, and the
+            // persisted master wins over add order)
+            let mut inner = house.inner.write().await;
+            if let Some(room_state) = inner.device_by_room.get_mut(room) {
+                room_state.capacity = state.capacity;
+                room_state.restricted = state.restricted;
+                // keep the device auto-assigned as master when the snapshot
+                // names none, so a populated room is never left master-less
+                if state.master.is_some() {
+                    room_state.master = state.master.clone();
+                }
+            }
+        }
+        Ok(house)
+    }
+
+    pub async fn name(&self) -> String {
+        self.inner.read().await.name.clone()
+    }
+
+    pub async fn rooms(&self) -> Vec<String> {
+        self.inner.read().await.device_by_room.keys().cloned().collect()
+    }
+
+    pub async fn devices(&self, room: &str) -> Result<Vec<String>, NoSuchRoom> {
+        let inner = self.inner.read().await;
+        let state = inner
             .device_by_room
             .get(room)
             .ok_or(WhereAmI(room.to_owned()))?;
-        Ok(devices.keys().cloned().collect())
+        Ok(state.devices.keys().cloned().collect())
+    }
+
+    /// Returns a handle to `room`, creating it if it does not exist yet.
+    pub async fn get_or_create_room(&self, room: &str) -> RoomHandle {
+        let mut inner = self.inner.write().await;
+        if !inner.device_by_room.contains_key(room) {
+            inner
+                .device_by_room
+                .insert(room.to_owned(), Room::default());
+            inner.metrics.rooms_active.inc();
+        }
+        RoomHandle {
+            name: room.to_owned(),
+            inner: self.inner.clone(),
+        }
     }
 
-    pub fn add_socket_to_room(
-        &mut self,
-        socket: Rc<Socket>,
+    pub async fn add_device_to_room(
+        &self,
+        device: Arc<dyn Device>,
         room: &str,
-    ) -> Result<(), AlreadyContainsDevice> {
-        // позволяет добавлять помещения
-        // позволяет добавлять устройства
-        if let Ok(devices) = self.devices(room) {
-            if devices.contains(&socket.name()) {
-                return Err(AlreadyContainsDevice(socket.name.to_owned()));
+    ) -> Result<(), JoinRoomError> {
+        self.get_or_create_room(room).await.add_device(device).await
+    }
+
+    /// Sets an optional capacity (maximum number of devices) for `room`,
+    /// creating the room if needed.
+    pub async fn set_room_capacity(&self, room: &str, capacity: Option<usize>) {
+        let mut inner = self.inner.write().await;
+        if !inner.device_by_room.contains_key(room) {
+            inner
+                .device_by_room
+                .insert(room.to_owned(), Room::default());
+            inner.metrics.rooms_active.inc();
+        }
+        inner.device_by_room.get_mut(room).unwrap().capacity = capacity;
+        let _ = inner.persist().await;
+    }
+
+    /// Marks `room` as restricted (or not), creating the room if needed.
+    /// Restricted rooms reject new devices.
+    pub async fn set_room_restricted(&self, room: &str, restricted: bool) {
+        let mut inner = self.inner.write().await;
+        if !inner.device_by_room.contains_key(room) {
+            inner
+                .device_by_room
+                .insert(room.to_owned(), Room::default());
+            inner.metrics.rooms_active.inc();
+        }
+        inner.device_by_room.get_mut(room).unwrap().restricted = restricted;
+        let _ = inner.persist().await;
+    }
+
+    pub async fn remove_room(&self, room: &str) {
+        let mut inner = self.inner.write().await;
+        // позволяет удалять помещения
+        // Also, remove all devices in that room from the device storage
+        if let Some(removed) = inner.device_by_room.remove(room) {
+            for device in removed.devices.values().cloned().collect::<Vec<_>>() {
+                inner.devices.remove(&device);
+                inner.metrics.devices_active.dec();
             }
+            inner.metrics.rooms_active.dec();
+            inner.subscribers.remove(room);
+            let _ = inner.persist().await;
         }
+    }
 
-        self.device_by_room
+    /// Subscribes to the live feed of [`DeviceUpdate`]s for `room`.
+    ///
+    /// Every [`poll`](Self::poll) pushes an update for each device in the room
+    /// whose read succeeded to all of its subscribers; failed reads send none.
+    pub async fn subscribe(&self, room: &str) -> broadcast::Receiver<DeviceUpdate> {
+        let mut inner = self.inner.write().await;
+        inner
+            .subscribers
             .entry(room.to_owned())
-            .or_insert(HashMap::new());
-        self.device_by_room
-            .get_mut(room)
-            .unwrap()
-            .insert(socket.name.clone(), socket.clone());
-        self.sockets.add(socket.clone());
-        Ok(())
+            .or_insert_with(|| broadcast::channel(64).0)
+            .subscribe()
     }
 
-    pub fn remove_room(&mut self, room: &str) {
-        // позволяет удалять помещения
-        self.device_by_room.remove(room);
-        // Also, remove all devides in that room from the device storage
+    /// Removes a device from a room, reporting the typed outcome: whether the
+    /// room emptied and was torn down, and if it remains, whether the master
+    /// left and which device was promoted in its place.
+    pub async fn remove_device_from_room(
+        &self,
+        room: &str,
+        device: &DeviceHandle,
+    ) -> LeaveRoomResult {
+        let mut inner = self.inner.write().await;
+        let name = device.name();
+
+        let state = match inner.device_by_room.get_mut(room) {
+            Some(state) => state,
+            None => return LeaveRoomResult::RoomDoesNotExist,
+        };
+        if state.devices.remove(&name).is_none() {
+            return LeaveRoomResult::DeviceNotPresent;
+        }
+
+        let was_master = state.master.as_deref() == Some(name.as_str());
+        let mut new_master = None;
+        if was_master {
+            new_master = state.devices.keys().next().cloned();
+            state.master = new_master.clone();
+        }
+        let is_empty = state.devices.is_empty();
+
+        inner.metrics.devices_active.dec();
+        inner.devices.remove(device);
+
+        let result = if is_empty {
+            inner.device_by_room.remove(room);
+            inner.metrics.rooms_active.dec();
+            LeaveRoomResult::RoomRemoved
+        } else {
+            LeaveRoomResult::RoomRemains {
+                was_master,
+                new_master,
+            }
+        };
+        let _ = inner.persist().await;
+        result
     }
 
-    pub fn remove_socket_from_room(&mut self, room: &str, socket: Rc<Socket>) {
-        if let Some(devices_in_room) = self.device_by_room.get_mut(room) {
-            devices_in_room.remove(&socket.name);
+    /// Fans a poll out across every device concurrently, broadcasting a
+    /// [`DeviceUpdate`] to each room's subscribers and returning each device's
+    /// result so callers can see which reads failed.
+    pub async fn poll(&self) -> Vec<(String, Result<f64, PollError>)> {
+        let targets: Vec<(String, DeviceHandle)> = {
+            let inner = self.inner.read().await;
+            inner.metrics.poll_total.inc();
+            let mut targets = Vec::new();
+            for (room, state) in inner.device_by_room.iter() {
+                for handle in state.devices.values() {
+                    targets.push((room.clone(), handle.clone()));
+                }
+            }
+            targets
+        };
+        let tasks: Vec<_> = targets
+            .into_iter()
+            .map(|(room, handle)| {
+                tokio::spawn(async move {
+                    let device = handle.name();
+                    let result = handle.poll().await;
+                    (room, device, result)
+                })
+            })
+            .collect();
+        let mut results = Vec::with_capacity(tasks.len());
+        let mut updates = Vec::new();
+        for task in tasks {
+            if let Ok((room, device, result)) = task.await {
+                if let Ok(voltage) = result {
+                    updates.push(DeviceUpdate {
+                        room,
+                        device: device.clone(),
+                        voltage,
+                    });
+                }
+                results.push((device, result));
+            }
+        }
+        // push a live event for every successful read to that room's subscribers
+        let inner = self.inner.read().await;
+        for update in updates {
+            if let Some(sender) = inner.subscribers.get(&update.room) {
+                let _ = sender.send(update);
+            }
         }
-        self.sockets
-            .devices
-            .retain(|sock| !Rc::ptr_eq(sock, &socket));
+        results
     }
 
-    pub fn poll(&self) {
-        for devices in self.device_by_room.values() {
-            for device in devices.values() {
-                device.poll();
+    /// Renders a snapshot of the house as text: a `House «name»:` header, then
+    /// each room's name followed by the `Display` output of its devices.
+    pub async fn render(&self) -> String {
+        let inner = self.inner.read().await;
+        let mut out = format!("House «{}»:\n", inner.name);
+        for (room, state) in inner.device_by_room.iter() {
+            out.push_str(room);
+            out.push('\n');
+            for device in state.devices.values() {
+                out.push_str(&device.to_string());
             }
         }
+        out
+    }
+
+    /// Gathers the registered metrics and renders them in the Prometheus text
+    /// exposition format, ready to serve to a scraper.
+    pub async fn metrics_text(&self) -> String {
+        let families = self.inner.read().await.metrics.registry.gather();
+        let mut buf = Vec::new();
+        TextEncoder::new().encode(&families, &mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
     }
 }
 
-impl Display for House {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("House «{}»:\n", self.name))?;
-        for (room, devices) in self.device_by_room.iter() {
-            room.fmt(f)?;
-            "\n".fmt(f)?;
-            for device in devices.values() {
-                device.fmt(f)?;
+impl RoomHandle {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub async fn add_device(&self, device: Arc<dyn Device>) -> Result<(), JoinRoomError> {
+        let mut inner = self.inner.write().await;
+        // позволяет добавлять помещения
+        // позволяет добавлять устройства
+        let name = device.name();
+        let room = inner.device_by_room.entry(self.name.clone()).or_default();
+        if room.restricted {
+            return Err(JoinRoomError::Restricted);
+        }
+        if room.devices.contains_key(&name) {
+            return Err(JoinRoomError::AlreadyContainsDevice(name));
+        }
+        if let Some(capacity) = room.capacity {
+            if room.devices.len() >= capacity {
+                return Err(JoinRoomError::Full);
             }
         }
+        let handle = DeviceHandle::spawn(device);
+        room.devices.insert(handle.name(), handle.clone());
+        // the first device added becomes the room's master
+        if room.master.is_none() {
+            room.master = Some(handle.name());
+        }
+        inner.devices.add(handle);
+        inner.metrics.devices_active.inc();
+        let _ = inner.persist().await;
         Ok(())
     }
+
+    pub async fn devices(&self) -> Vec<String> {
+        let inner = self.inner.read().await;
+        inner
+            .device_by_room
+            .get(&self.name)
+            .map(|state| state.devices.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// The name of the room's current master device, if any.
+    pub async fn master(&self) -> Option<String> {
+        let inner = self.inner.read().await;
+        inner
+            .device_by_room
+            .get(&self.name)
+            .and_then(|state| state.master.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn socket(name: &str) -> Arc<dyn Device> {
+        Arc::new(Socket::new(name))
+    }
+
+    #[test]
+    fn parse_voltage_reads_a_valid_line() {
+        assert_eq!(RemoteSocket::parse_voltage("VOLTS 220.5\n").unwrap(), 220.5);
+    }
+
+    #[test]
+    fn parse_voltage_rejects_a_missing_prefix() {
+        assert!(matches!(
+            RemoteSocket::parse_voltage("220.5\n"),
+            Err(PollError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn parse_voltage_rejects_a_non_numeric_reading() {
+        assert!(matches!(
+            RemoteSocket::parse_voltage("VOLTS nope\n"),
+            Err(PollError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn parse_voltage_rejects_an_empty_reply() {
+        assert!(matches!(
+            RemoteSocket::parse_voltage(""),
+            Err(PollError::Parse(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn add_device_is_rejected_when_the_room_is_full() {
+        let mut registry = Registry::new();
+        let house = HouseHandle::new("test", &mut registry).unwrap();
+        house.set_room_capacity("r", Some(1)).await;
+        house.add_device_to_room(socket("a"), "r").await.unwrap();
+        assert!(matches!(
+            house.add_device_to_room(socket("b"), "r").await,
+            Err(JoinRoomError::Full)
+        ));
+    }
+
+    #[tokio::test]
+    async fn add_device_is_rejected_when_the_room_is_restricted() {
+        let mut registry = Registry::new();
+        let house = HouseHandle::new("test", &mut registry).unwrap();
+        house.set_room_restricted("r", true).await;
+        assert!(matches!(
+            house.add_device_to_room(socket("a"), "r").await,
+            Err(JoinRoomError::Restricted)
+        ));
+    }
+
+    #[tokio::test]
+    async fn add_device_is_rejected_when_already_present() {
+        let mut registry = Registry::new();
+        let house = HouseHandle::new("test", &mut registry).unwrap();
+        house.add_device_to_room(socket("a"), "r").await.unwrap();
+        assert!(matches!(
+            house.add_device_to_room(socket("a"), "r").await,
+            Err(JoinRoomError::AlreadyContainsDevice(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn removing_the_master_promotes_another_device() {
+        let mut registry = Registry::new();
+        let house = HouseHandle::new("test", &mut registry).unwrap();
+        let room = house.get_or_create_room("r").await;
+        house.add_device_to_room(socket("a"), "r").await.unwrap();
+        house.add_device_to_room(socket("b"), "r").await.unwrap();
+        assert_eq!(room.master().await, Some("a".to_owned()));
+
+        let leaving = DeviceHandle::spawn(socket("a"));
+        let result = house.remove_device_from_room("r", &leaving).await;
+        assert_eq!(
+            result,
+            LeaveRoomResult::RoomRemains {
+                was_master: true,
+                new_master: Some("b".to_owned()),
+            }
+        );
+        assert_eq!(room.master().await, Some("b".to_owned()));
+    }
+
+    #[tokio::test]
+    async fn save_and_load_round_trips_topology_and_constraints() {
+        let path = std::env::temp_dir().join("rusty_plug_round_trip.json");
+
+        let mut registry = Registry::new();
+        let house = HouseHandle::new("test", &mut registry).unwrap();
+        house
+            .add_device_to_room(socket("a"), "bedroom")
+            .await
+            .unwrap();
+        house.set_room_capacity("bedroom", Some(4)).await;
+        // an empty, restricted room that must survive the round trip
+        house.set_room_restricted("cellar", true).await;
+        house.save(&path).await.unwrap();
+
+        let mut reloaded_registry = Registry::new();
+        let reloaded = HouseHandle::load(&path, &mut reloaded_registry)
+            .await
+            .unwrap();
+
+        let mut rooms = reloaded.rooms().await;
+        rooms.sort();
+        assert_eq!(rooms, vec!["bedroom".to_owned(), "cellar".to_owned()]);
+        assert_eq!(
+            reloaded.devices("bedroom").await.unwrap(),
+            vec!["a".to_owned()]
+        );
+
+        let bedroom = reloaded.get_or_create_room("bedroom").await;
+        assert_eq!(bedroom.master().await, Some("a".to_owned()));
+        // the restricted flag survived, so the empty cellar still rejects devices
+        assert!(matches!(
+            reloaded.add_device_to_room(socket("x"), "cellar").await,
+            Err(JoinRoomError::Restricted)
+        ));
+    }
 }
@@ -1,49 +1,99 @@
-use smart_home_with_rc::{House, Socket};
-use std::rc::Rc;
+use prometheus::Registry;
+use smart_home_with_rc::{Device, HouseHandle, RemoteSocket, Socket, Thermometer};
+use std::sync::Arc;
 
-fn main() {
+#[tokio::main]
+async fn main() {
     let socket = Socket::new("Hello");
     println!("{}", socket);
 
-    let socket1 = Rc::new(socket);
-    let mut house = House::new("The Rising Sun");
+    let socket1: Arc<dyn Device> = Arc::new(socket);
+    let mut registry = Registry::new();
+    let house = HouseHandle::new("The Rising Sun", &mut registry).expect("Should register metrics");
     house
-        .add_socket_to_room(socket1.clone(), "bedroom")
+        .add_device_to_room(socket1.clone(), "bedroom")
+        .await
         .expect("");
 
-    println!("The house is: \n{}", house);
-    let socket2 = Rc::new(Socket::new("My other socket"));
+    println!("The house is: \n{}", house.render().await);
+    let socket2: Arc<dyn Device> = Arc::new(Socket::new("My other socket"));
     house
-        .add_socket_to_room(socket2, "bedroom")
+        .add_device_to_room(socket2, "bedroom")
+        .await
         .expect("Should add");
-    let socket3 = Rc::new(Socket::new("Hello"));
+    let thermometer: Arc<dyn Device> = Arc::new(Thermometer::new("Bedroom thermometer"));
     house
-        .add_socket_to_room(socket3.clone(), "bedroom")
+        .add_device_to_room(thermometer, "bedroom")
+        .await
+        .expect("Should add");
+    let remote: Arc<dyn Device> = Arc::new(RemoteSocket::new("Shed socket", "127.0.0.1:9009"));
+    house
+        .add_device_to_room(remote, "shed")
+        .await
+        .expect("Should add");
+    let socket3: Arc<dyn Device> = Arc::new(Socket::new("Hello"));
+    house
+        .add_device_to_room(socket3.clone(), "bedroom")
+        .await
         .expect_err("Should get error");
-    println!("The house AFTER ADDING ANOTHER SOCKET is: \n{}", house);
-    house.poll();
-    println!("The house AFTER POLLING is: \n{}", house);
-    println!("Rooms in the house are: {:?}", house.rooms());
-
-    let socket4 = Rc::new(Socket::new("Hello"));
-    let res = house.add_socket_to_room(socket4, "bedroom");
-    if let Err(e) = res {
-        println!("{}", e);
+    println!(
+        "The house AFTER ADDING ANOTHER SOCKET is: \n{}",
+        house.render().await
+    );
+    let mut bedroom_feed = house.subscribe("bedroom").await;
+    let results = house.poll().await;
+    println!("The house AFTER POLLING is: \n{}", house.render().await);
+    while let Ok(update) = bedroom_feed.try_recv() {
+        println!(
+            "Live update: {} in {} is now {:.2}",
+            update.device, update.room, update.voltage
+        );
     }
+    for (device, result) in &results {
+        match result {
+            Ok(voltage) => println!("{} read {:.2}", device, voltage),
+            Err(e) => println!("{} failed to poll: {}", device, e),
+        }
+    }
+    println!("Rooms in the house are: {:?}", house.rooms().await);
 
-    let res = house.devices("No such room");
+    let res = house.devices("No such room").await;
     if let Err(e) = res {
         println!("Composed error: {}", e);
     }
 
-    house.remove_socket_from_room("bedroom", socket3);
-    println!("The house AFTER REMOVING socket is: \n{}", house);
-
-    let devices_in_bedroom = house.devices("bedroom");
+    let devices_in_bedroom = house.devices("bedroom").await;
     match devices_in_bedroom {
         Ok(devices) => println!("Devices in bedroom are {:?}", devices),
         Err(_) => println!("There's been an error"),
     }
-    house.remove_room("bedroom");
-    println!("The house AFTER REMOVING bedroom is: \n{}", house);
+    // A capacity-limited room with a master device.
+    house.set_room_capacity("garage", Some(1)).await;
+    let garage = house.get_or_create_room("garage").await;
+    let garage_socket: Arc<dyn Device> = Arc::new(Socket::new("Garage socket"));
+    garage
+        .add_device(garage_socket)
+        .await
+        .expect("Should add the first device");
+    println!("Garage master is: {:?}", garage.master().await);
+    let overflow: Arc<dyn Device> = Arc::new(Socket::new("One too many"));
+    match garage.add_device(overflow).await {
+        Ok(()) => println!("Unexpectedly added an overflow device"),
+        Err(e) => println!("Garage rejected the overflow device: {}", e),
+    }
+
+    house.remove_room("bedroom").await;
+    println!(
+        "The house AFTER REMOVING bedroom is: \n{}",
+        house.render().await
+    );
+
+    house.save("house_state.json").await.expect("Should save");
+    let mut reloaded_registry = Registry::new();
+    let reloaded = HouseHandle::load("house_state.json", &mut reloaded_registry)
+        .await
+        .expect("Should load");
+    println!("The RELOADED house is: \n{}", reloaded.render().await);
+
+    println!("Metrics:\n{}", house.metrics_text().await);
 }